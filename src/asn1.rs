@@ -178,7 +178,10 @@ pub enum ASN1Value {
     NoSuchInstance,
     EndOfMibView,
     // maybe this should just be a generic ASN1Value and let the callers decode it to a trap
-    Trap(Sequence)
+    Trap(Sequence),
+    SnmpV2Trap(Sequence),
+    InformRequest(Sequence),
+    GetResponse(Sequence)
 }
 
 
@@ -230,6 +233,9 @@ impl ASN1Value {
         match self {
             ASN1Value::Sequence(v) => Ok(v),
             ASN1Value::Trap(v) => Ok(v),
+            ASN1Value::SnmpV2Trap(v) => Ok(v),
+            ASN1Value::InformRequest(v) => Ok(v),
+            ASN1Value::GetResponse(v) => Ok(v),
             _ => Err(Error::WrongType)
         }
     }
@@ -316,6 +322,15 @@ pub fn decode_value(c: &mut ASN1Reader) -> ASN1Result<ASN1Value> {
         Trap => {
             Ok(ASN1Value::Trap(try!(read_sequence(c, length))))
         },
+        SnmpV2Trap => {
+            Ok(ASN1Value::SnmpV2Trap(try!(read_sequence(c, length))))
+        },
+        InformRequest => {
+            Ok(ASN1Value::InformRequest(try!(read_sequence(c, length))))
+        },
+        GetResponse => {
+            Ok(ASN1Value::GetResponse(try!(read_sequence(c, length))))
+        },
         _ => {
             Err(Error::WrongType)
         }
@@ -330,7 +345,7 @@ fn read_byte(reader: &mut ASN1Reader) -> ASN1Result<u8> {
 fn read_length(reader: &mut ASN1Reader) -> ASN1Result<usize> {
     let length = try!(read_byte(reader));
 
-    if length < 127 {
+    if length < 128 {
         Ok(length as usize)
     } else {
         let num_octets = length & 127;
@@ -355,18 +370,18 @@ fn read_integer(reader: &mut ASN1Reader, len: usize) -> ASN1Result<i64> {
 
 fn read_base128int(reader: &mut ASN1Reader) -> ASN1Result<u32> {
     let mut r = 0;
-    loop {
-        if r > 4 {
-            return Err(Error::UnexpectedValue)
-        }
-        r <<= 8;
+    // A u32 needs at most 5 base-128 digits (ceil(32/7)); bound the byte
+    // count rather than the accumulated value, which says nothing about
+    // how many bytes have been consumed.
+    for _ in 0..5 {
+        r <<= 7;
         let b = try!(read_byte(reader));
         r += (b & 0x7f) as u32;
         if b & 0x80 == 0 {
-            break
+            return Ok(r)
         }
     }
-    Ok(r)
+    Err(Error::UnexpectedValue)
 }
 
 fn read_oid(r: &mut ASN1Reader, len: usize) -> ASN1Result<ObjectIdentifier> {
@@ -412,3 +427,302 @@ fn read_ip_address(reader: &mut ASN1Reader, size: usize) -> ASN1Result<IpAddr> {
     }
 }
 
+pub fn to_bytes(v: &ASN1Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_value(v, &mut out);
+    out
+}
+
+pub fn encode_value(v: &ASN1Value, out: &mut Vec<u8>) {
+    match *v {
+        ASN1Value::EndOfContents => write_header(out, ASN1Type::EndOfContents, 0),
+        ASN1Value::Sequence(ref items) => encode_children(ASN1Type::Sequence, items, out),
+        ASN1Value::Trap(ref items) => encode_children(ASN1Type::Trap, items, out),
+        ASN1Value::SnmpV2Trap(ref items) => encode_children(ASN1Type::SnmpV2Trap, items, out),
+        ASN1Value::InformRequest(ref items) => encode_children(ASN1Type::InformRequest, items, out),
+        ASN1Value::GetResponse(ref items) => encode_children(ASN1Type::GetResponse, items, out),
+        ASN1Value::Boolean(b) => {
+            write_header(out, ASN1Type::Boolean, 1);
+            out.push(if b { 0xff } else { 0x00 });
+        },
+        ASN1Value::Integer(i) => {
+            let bytes = signed_int_octets(i);
+            write_header(out, ASN1Type::Integer, bytes.len());
+            out.extend_from_slice(&bytes);
+        },
+        ASN1Value::BitString(ref bits) => {
+            let unused = (8 - (bits.len() % 8)) % 8;
+            let bytes = bits.to_bytes();
+            write_header(out, ASN1Type::BitString, bytes.len() + 1);
+            out.push(unused as u8);
+            out.extend_from_slice(&bytes);
+        },
+        ASN1Value::OctetString(ref s) => {
+            write_header(out, ASN1Type::OctetString, s.len());
+            out.extend_from_slice(s.as_bytes());
+        },
+        ASN1Value::Null => write_header(out, ASN1Type::Null, 0),
+        ASN1Value::ObjectIdentifier(ref oid) => {
+            let bytes = encode_oid(oid);
+            write_header(out, ASN1Type::ObjectIdentifier, bytes.len());
+            out.extend_from_slice(&bytes);
+        },
+        ASN1Value::ObjectDescription(ref s) => {
+            write_header(out, ASN1Type::ObjectDescription, s.len());
+            out.extend_from_slice(s.as_bytes());
+        },
+        ASN1Value::IPAddress(addr) => {
+            let bytes = encode_ip_address(addr);
+            write_header(out, ASN1Type::IPAddress, bytes.len());
+            out.extend_from_slice(&bytes);
+        },
+        ASN1Value::Counter32(x) => encode_unsigned(ASN1Type::Counter32, x as u64, out),
+        ASN1Value::Gauge32(x) => encode_unsigned(ASN1Type::Gauge32, x as u64, out),
+        ASN1Value::TimeTicks(x) => encode_unsigned(ASN1Type::TimeTicks, x as u64, out),
+        ASN1Value::Opaque(ref bytes) => {
+            write_header(out, ASN1Type::Opaque, bytes.len());
+            out.extend_from_slice(bytes);
+        },
+        ASN1Value::NsapAddress(ref bytes) => {
+            write_header(out, ASN1Type::NsapAddress, bytes.len());
+            out.extend_from_slice(bytes);
+        },
+        ASN1Value::Counter64(x) => encode_unsigned(ASN1Type::Counter64, x, out),
+        ASN1Value::Uinteger32(x) => encode_unsigned(ASN1Type::Uinteger32, x as u64, out),
+        ASN1Value::NoSuchObject => write_header(out, ASN1Type::NoSuchObject, 0),
+        ASN1Value::NoSuchInstance => write_header(out, ASN1Type::NoSuchInstance, 0),
+        ASN1Value::EndOfMibView => write_header(out, ASN1Type::EndOfMibView, 0),
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, tag: ASN1Type, len: usize) {
+    out.push(tag as u8);
+    write_length(out, len);
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 128 {
+        out.push(len as u8);
+    } else {
+        let mut octets = Vec::new();
+        let mut n = len;
+        while n > 0 {
+            octets.push((n & 0xff) as u8);
+            n >>= 8;
+        }
+        octets.reverse();
+        out.push(0x80 | octets.len() as u8);
+        out.extend_from_slice(&octets);
+    }
+}
+
+fn encode_children(tag: ASN1Type, items: &[ASN1Value], out: &mut Vec<u8>) {
+    let mut body = Vec::new();
+    for item in items {
+        encode_value(item, &mut body);
+    }
+    write_header(out, tag, body.len());
+    out.extend_from_slice(&body);
+}
+
+fn signed_int_octets(v: i64) -> Vec<u8> {
+    let mut bytes = vec![
+        (v >> 56) as u8, (v >> 48) as u8, (v >> 40) as u8, (v >> 32) as u8,
+        (v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8
+    ];
+    while bytes.len() > 1 &&
+        ((bytes[0] == 0x00 && bytes[1] & 0x80 == 0) ||
+         (bytes[0] == 0xff && bytes[1] & 0x80 != 0)) {
+        bytes.remove(0);
+    }
+    bytes
+}
+
+fn encode_unsigned(tag: ASN1Type, v: u64, out: &mut Vec<u8>) {
+    let mut bytes = vec![
+        (v >> 56) as u8, (v >> 48) as u8, (v >> 40) as u8, (v >> 32) as u8,
+        (v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8
+    ];
+    while bytes.len() > 1 && bytes[0] == 0x00 {
+        bytes.remove(0);
+    }
+    if bytes[0] & 0x80 != 0 {
+        bytes.insert(0, 0x00);
+    }
+    write_header(out, tag, bytes.len());
+    out.extend_from_slice(&bytes);
+}
+
+fn encode_base128(v: u32, out: &mut Vec<u8>) {
+    let mut chunks = vec![(v & 0x7f) as u8];
+    let mut rest = v >> 7;
+    while rest > 0 {
+        chunks.push(((rest & 0x7f) as u8) | 0x80);
+        rest >>= 7;
+    }
+    chunks.reverse();
+    out.extend_from_slice(&chunks);
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if oid.len() >= 2 {
+        out.push((oid[0] * 40 + oid[1]) as u8);
+        for arc in &oid[2..] {
+            encode_base128(*arc, &mut out);
+        }
+    }
+    out
+}
+
+fn encode_ip_address(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec()
+    }
+}
+
+/// Renders a decoded value as a tagged JSON object, e.g.
+/// `{"type":"Counter32","value":42}`. There's no `serde` dependency here,
+/// so this builds the JSON text directly.
+pub fn to_json(v: &ASN1Value) -> String {
+    match *v {
+        ASN1Value::EndOfContents => r#"{"type":"EndOfContents"}"#.to_owned(),
+        ASN1Value::Sequence(ref items) => json_tagged_array("Sequence", items),
+        ASN1Value::Trap(ref items) => json_tagged_array("Trap", items),
+        ASN1Value::SnmpV2Trap(ref items) => json_tagged_array("SnmpV2Trap", items),
+        ASN1Value::InformRequest(ref items) => json_tagged_array("InformRequest", items),
+        ASN1Value::GetResponse(ref items) => json_tagged_array("GetResponse", items),
+        ASN1Value::Boolean(b) => format!(r#"{{"type":"Boolean","value":{}}}"#, b),
+        ASN1Value::Integer(i) => format!(r#"{{"type":"Integer","value":{}}}"#, i),
+        ASN1Value::BitString(ref bits) => format!(r#"{{"type":"BitString","value":{}}}"#, json_string(&bytes_to_text(&bits.to_bytes()))),
+        ASN1Value::OctetString(ref s) => format!(r#"{{"type":"OctetString","value":{}}}"#, json_string(s)),
+        ASN1Value::Null => r#"{"type":"Null"}"#.to_owned(),
+        ASN1Value::ObjectIdentifier(ref oid) => format!(r#"{{"type":"ObjectIdentifier","value":{}}}"#, json_string(&oid_to_string(oid))),
+        ASN1Value::ObjectDescription(ref s) => format!(r#"{{"type":"ObjectDescription","value":{}}}"#, json_string(s)),
+        ASN1Value::IPAddress(addr) => format!(r#"{{"type":"IPAddress","value":{}}}"#, json_string(&addr.to_string())),
+        ASN1Value::Counter32(x) => format!(r#"{{"type":"Counter32","value":{}}}"#, x),
+        ASN1Value::Gauge32(x) => format!(r#"{{"type":"Gauge32","value":{}}}"#, x),
+        ASN1Value::TimeTicks(x) => format!(r#"{{"type":"TimeTicks","value":{}}}"#, x),
+        ASN1Value::Opaque(ref bytes) => format!(r#"{{"type":"Opaque","value":{}}}"#, json_string(&bytes_to_text(bytes))),
+        ASN1Value::NsapAddress(ref bytes) => format!(r#"{{"type":"NsapAddress","value":{}}}"#, json_string(&bytes_to_text(bytes))),
+        ASN1Value::Counter64(x) => format!(r#"{{"type":"Counter64","value":{}}}"#, x),
+        ASN1Value::Uinteger32(x) => format!(r#"{{"type":"Uinteger32","value":{}}}"#, x),
+        ASN1Value::NoSuchObject => r#"{"type":"NoSuchObject"}"#.to_owned(),
+        ASN1Value::NoSuchInstance => r#"{"type":"NoSuchInstance"}"#.to_owned(),
+        ASN1Value::EndOfMibView => r#"{"type":"EndOfMibView"}"#.to_owned()
+    }
+}
+
+fn json_tagged_array(type_name: &str, items: &[ASN1Value]) -> String {
+    let parts: Vec<String> = items.iter().map(to_json).collect();
+    format!(r#"{{"type":"{}","value":[{}]}}"#, type_name, parts.join(","))
+}
+
+/// Renders `oid` as a dotted string, e.g. `1.3.6.1.6.3.1.1.5`.
+pub fn oid_to_string(oid: &[u32]) -> String {
+    oid.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// Decodes `bytes` as UTF-8 when possible, falling back to lowercase hex
+/// for arbitrary binary payloads (e.g. `Opaque`, `NsapAddress`).
+fn bytes_to_text(bytes: &[u8]) -> String {
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(s) => s,
+        Err(_) => bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join("")
+    }
+}
+
+/// Escapes `s` as a JSON string literal, including the surrounding quotes.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn round_trip(v: ASN1Value) -> ASN1Value {
+        let bytes = to_bytes(&v);
+        let mut c = io::Cursor::new(&bytes[..]);
+        decode_value(&mut c).unwrap()
+    }
+
+    #[test]
+    fn round_trips_a_sequence_of_primitive_values() {
+        let seq = ASN1Value::Sequence(vec![
+            ASN1Value::Integer(42),
+            ASN1Value::OctetString("public".to_owned()),
+            ASN1Value::ObjectIdentifier(vec![1, 3, 6, 1, 6, 3].into_boxed_slice()),
+            ASN1Value::IPAddress(IpAddr::V4(Ipv4Addr::new(23, 3, 3, 4))),
+            ASN1Value::TimeTicks(123456),
+        ].into_boxed_slice());
+
+        match round_trip(seq) {
+            ASN1Value::Sequence(items) => {
+                assert_eq!(items[0].clone().as_i64().unwrap(), 42);
+                assert_eq!(items[1].clone().as_string().unwrap(), "public");
+                assert!(oid_equals(&[1, 3, 6, 1, 6, 3], &items[2].clone().as_oid().unwrap()));
+                assert_eq!(items[3].clone().as_ipaddr().unwrap(), IpAddr::V4(Ipv4Addr::new(23, 3, 3, 4)));
+                assert_eq!(items[4].clone().as_u32().unwrap(), 123456);
+            },
+            _ => panic!("expected a sequence back")
+        }
+    }
+
+    // Microsoft's enterprise number (311) needs a multi-byte base-128 arc;
+    // every arc in the other round-trip test above is < 128 and so can't
+    // catch a wrong shift width in read_base128int.
+    #[test]
+    fn round_trips_an_oid_with_an_arc_over_127() {
+        let oid = ASN1Value::ObjectIdentifier(vec![1, 3, 6, 1, 4, 1, 311].into_boxed_slice());
+
+        match round_trip(oid) {
+            ASN1Value::ObjectIdentifier(out) => assert!(oid_equals(&[1, 3, 6, 1, 4, 1, 311], &out)),
+            _ => panic!("expected an object identifier back")
+        }
+    }
+
+    // An arc whose first continuation byte's low 7 bits exceed 4 (642 = 5
+    // * 128 + 2, encoded 0x85 0x02) used to trip read_base128int's loop
+    // guard, which compared the accumulated value instead of a byte count.
+    #[test]
+    fn round_trips_an_oid_whose_first_continuation_byte_exceeds_four() {
+        let oid = ASN1Value::ObjectIdentifier(vec![1, 3, 6, 1, 4, 1, 642].into_boxed_slice());
+
+        match round_trip(oid) {
+            ASN1Value::ObjectIdentifier(out) => assert!(oid_equals(&[1, 3, 6, 1, 4, 1, 642], &out)),
+            _ => panic!("expected an object identifier back")
+        }
+    }
+
+    // A 127-byte OCTET STRING is the boundary case for BER's short-form
+    // length encoding (the last value `< 128`, not `< 127`).
+    #[test]
+    fn round_trips_a_value_with_a_127_byte_length() {
+        let s = ASN1Value::OctetString("x".repeat(127));
+        let bytes = to_bytes(&s);
+        assert_eq!(bytes[1], 127);
+
+        match round_trip(s) {
+            ASN1Value::OctetString(out) => assert_eq!(out.len(), 127),
+            _ => panic!("expected an octet string back")
+        }
+    }
+}
+
@@ -3,11 +3,12 @@ extern crate byteorder;
 extern crate bit_vec;
 extern crate libc;
 extern crate crossbeam;
+extern crate crypto;
 
 mod asn1;
 mod snmp;
 mod server;
 
 fn main() {
-    server::run_server("127.0.0.1:1062");
+    server::run_server("127.0.0.1:1062", server::LoggingHandler, Vec::new(), Some(snmp::mib::OidMap::with_defaults()));
 }
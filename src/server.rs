@@ -1,25 +1,169 @@
 use std::net::*;
+use std::io::Write;
 use snmp::*;
+use snmp::usm;
+use snmp::mib;
+use asn1;
 use crossbeam;
 
-pub struct Server {
-    num_packets: u64
+pub trait TrapHandler {
+    fn on_trap(&mut self, src: SocketAddr, pkt: &SnmpPacket, mib: Option<&mib::OidMap>);
 }
 
-impl Server {
-    fn new() -> Server {
+pub struct LoggingHandler;
+
+impl TrapHandler for LoggingHandler {
+    fn on_trap(&mut self, src: SocketAddr, pkt: &SnmpPacket, mib: Option<&mib::OidMap>) {
+        match *pkt {
+            SnmpPacket::V1(ref v1) => {
+                match v1.pdu {
+                    SnmpV1PDU::Trap(ref trap) => {
+                        match mib {
+                            Some(m) => println!("trap! from {}: {}", src, trap.render_varbinds(m)),
+                            None => println!("trap! from {}", src)
+                        }
+                    }
+                }
+            },
+            SnmpPacket::V2c(ref v2c) => {
+                match v2c.pdu {
+                    SnmpV2PDU::Trap(ref trap) => {
+                        match mib {
+                            Some(m) => println!("trap v2! from {}: {}", src, trap.render_varbinds(m)),
+                            None => println!("trap v2! from {}", src)
+                        }
+                    },
+                    SnmpV2PDU::Inform(_) => println!("trap v2! from {}", src)
+                }
+            },
+            SnmpPacket::V3(_) => println!("trap v3! from {}", src)
+        }
+    }
+}
+
+pub struct FanoutHandler {
+    handlers: Vec<Box<TrapHandler>>
+}
+
+impl FanoutHandler {
+    pub fn new(handlers: Vec<Box<TrapHandler>>) -> FanoutHandler {
+        FanoutHandler {
+            handlers: handlers
+        }
+    }
+}
+
+impl TrapHandler for FanoutHandler {
+    fn on_trap(&mut self, src: SocketAddr, pkt: &SnmpPacket, mib: Option<&mib::OidMap>) {
+        for h in self.handlers.iter_mut() {
+            h.on_trap(src, pkt, mib);
+        }
+    }
+}
+
+/// Writes one JSON object per line to `writer`, e.g. for piping traps into
+/// a log shipper. Only decoded traps produce a line; informs and reports
+/// have no source packet shape worth logging here.
+pub struct JsonHandler<W: Write> {
+    writer: W
+}
+
+impl<W: Write> JsonHandler<W> {
+    pub fn new(writer: W) -> JsonHandler<W> {
+        JsonHandler {
+            writer: writer
+        }
+    }
+}
+
+impl<W: Write> TrapHandler for JsonHandler<W> {
+    fn on_trap(&mut self, src: SocketAddr, pkt: &SnmpPacket, _mib: Option<&mib::OidMap>) {
+        let json = match *pkt {
+            SnmpPacket::V1(ref v1) => {
+                match v1.pdu {
+                    SnmpV1PDU::Trap(ref trap) => Some(trap.to_json(&v1.community, src))
+                }
+            },
+            SnmpPacket::V2c(ref v2c) => {
+                match v2c.pdu {
+                    SnmpV2PDU::Trap(ref trap) => Some(trap.to_json(&v2c.community, src)),
+                    SnmpV2PDU::Inform(_) => None
+                }
+            },
+            SnmpPacket::V3(ref v3) => {
+                match v3.pdu {
+                    SnmpV2PDU::Trap(ref trap) => Some(trap.to_json_v3(&v3.user_name, src)),
+                    SnmpV2PDU::Inform(_) => None
+                }
+            }
+        };
+        if let Some(line) = json {
+            if let Err(e) = writeln!(self.writer, "{}", line) {
+                println!("failed to write json trap: {:?}", e);
+            }
+        }
+    }
+}
+
+pub struct Server<H: TrapHandler> {
+    num_packets: u64,
+    handler: H,
+    users: Vec<usm::User>,
+    time_state: usm::TimeWindowState,
+    mib: Option<mib::OidMap>
+}
+
+impl<H: TrapHandler> Server<H> {
+    fn new(handler: H, users: Vec<usm::User>, mib: Option<mib::OidMap>) -> Server<H> {
         Server {
-            num_packets: 0
+            num_packets: 0,
+            handler: handler,
+            users: users,
+            time_state: usm::TimeWindowState::new(),
+            mib: mib
         }
     }
 
-    fn handle_packet(&mut self, p: SnmpPacket) {
+    fn handle_packet(&mut self, socket: &UdpSocket, src: SocketAddr, raw: &[u8]) {
+        let p = match SnmpPacket::new(raw, &self.users, &mut self.time_state) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("snmp packet parse error: {:?}", e);
+                return;
+            }
+        };
         self.num_packets += 1;
+        let mib = self.mib.as_ref();
         match p {
-            SnmpPacket::V1(v1) => {
+            SnmpPacket::V1(ref v1) => {
                 match v1.pdu {
-                    SnmpV1PDU::Trap(trap) => {
-                        println!("trap!");
+                    SnmpV1PDU::Trap(_) => self.handler.on_trap(src, &p, mib)
+                }
+            },
+            SnmpPacket::V2c(ref v2c) => {
+                match v2c.pdu {
+                    SnmpV2PDU::Trap(_) => self.handler.on_trap(src, &p, mib),
+                    SnmpV2PDU::Inform(ref inform) => {
+                        let response = build_get_response(&v2c.community, inform.request_id, &inform.variables);
+                        let bytes = asn1::to_bytes(&response);
+                        if let Err(e) = socket.send_to(&bytes, src) {
+                            println!("failed to send inform response: {:?}", e);
+                        }
+                    }
+                }
+            },
+            SnmpPacket::V3(ref v3) => {
+                match v3.pdu {
+                    SnmpV2PDU::Trap(_) => self.handler.on_trap(src, &p, mib),
+                    SnmpV2PDU::Inform(ref inform) => {
+                        let auth_protocol = self.users.iter().find(|u| u.name == v3.user_name)
+                            .map(|u| u.auth_protocol).unwrap_or(usm::AuthProtocol::Md5);
+                        let response = build_v3_get_response(&v3.security_params, v3.msg_id,
+                                                              v3.auth_key.as_ref().map(|k| k.as_slice()),
+                                                              auth_protocol, inform.request_id, &inform.variables);
+                        if let Err(e) = socket.send_to(&response, src) {
+                            println!("failed to send v3 inform response: {:?}", e);
+                        }
                     }
                 }
             }
@@ -28,38 +172,34 @@ impl Server {
 }
 use std::sync::*;
 
-pub fn run_server(addr: &str) {
-    let server = Mutex::new(Server::new());
+pub fn run_server<H: TrapHandler + Send>(addr: &str, handler: H, users: Vec<usm::User>, mib: Option<mib::OidMap>) {
+    let server = Mutex::new(Server::new(handler, users, mib));
     let queue = crossbeam::sync::MsQueue::new();
+    let socket = Arc::new(UdpSocket::bind(addr).unwrap());
+
     crossbeam::scope(|scope| {
-        scope.spawn(|| {
+        let recv_socket = socket.clone();
+        scope.spawn(move || {
             let mut bytes = [0; 4096];
-            let socket = UdpSocket::bind(addr).unwrap();
             loop {
-                let (num_bytes, _) = match socket.recv_from(&mut bytes) {
+                let (num_bytes, src) = match recv_socket.recv_from(&mut bytes) {
                     Ok(x) => x,
                     Err(e) => {
                         println!("socket error: {:?}", e);
                         continue;
                     }
                 };
-                let pkt = match SnmpPacket::new(&bytes[..num_bytes]) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        println!("snmp packet parse error: {:?}", e);
-                        continue;
-                    }
-                };
                 println!("pushing to queue");
-                queue.push(pkt);
+                queue.push((src, bytes[..num_bytes].to_vec()));
             }
         });
         for _ in 0..2 {
-            scope.spawn(|| {
+            let send_socket = socket.clone();
+            scope.spawn(move || {
                 loop {
-                    let val = queue.pop();
+                    let (src, raw) = queue.pop();
                     if let Ok(mut s) = server.try_lock() {
-                        s.handle_packet(val);
+                        s.handle_packet(&send_socket, src, &raw);
                     }
                 }
             });
@@ -0,0 +1,153 @@
+// Symbolic OID resolution: a small trie mapping numeric OID arcs to names,
+// seeded with common translations and extensible from a text file at
+// startup.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+
+#[derive(Debug, Default)]
+struct OidNode {
+    name: Option<String>,
+    children: HashMap<u32, OidNode>
+}
+
+#[derive(Debug, Default)]
+pub struct OidMap {
+    root: OidNode
+}
+
+impl OidMap {
+    pub fn new() -> OidMap {
+        OidMap { root: OidNode::default() }
+    }
+
+    pub fn with_defaults() -> OidMap {
+        let mut map = OidMap::new();
+        map.insert(&[1, 3, 6, 1, 6, 3, 1, 1, 5], "snmpTraps");
+        map.insert(&[1, 3, 6, 1, 6, 3, 1, 1, 4, 1], "snmpTrapOID");
+        map.insert(&[1, 3, 6, 1, 2, 1, 1, 3], "sysUpTime");
+        map.insert(&[1, 3, 6, 1, 2, 1, 1], "system");
+        map.insert(&[1, 3, 6, 1, 6, 3, 1, 1, 4], "snmpTrap");
+        map
+    }
+
+    pub fn insert(&mut self, oid: &[u32], name: &str) {
+        let mut node = &mut self.root;
+        for arc in oid {
+            node = node.children.entry(*arc).or_insert_with(OidNode::default);
+        }
+        node.name = Some(name.to_owned());
+    }
+
+    /// Loads additional `oid = name` lines from a text file (one mapping
+    /// per line, blank lines and `#` comments ignored), e.g. a locally
+    /// maintained MIB translation table.
+    pub fn load_file(&mut self, path: &str) -> io::Result<()> {
+        let file = try!(File::open(path));
+        let reader = io::BufReader::new(file);
+        for line in reader.lines() {
+            let line = try!(line);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let oid_part = match parts.next() {
+                Some(p) => p.trim(),
+                None => continue
+            };
+            let name_part = match parts.next() {
+                Some(p) => p.trim(),
+                None => continue
+            };
+            let oid: Result<Vec<u32>, _> = oid_part.split('.').map(|s| s.parse::<u32>()).collect();
+            let oid = match oid {
+                Ok(oid) => oid,
+                Err(_) => {
+                    println!("mib: skipping line with invalid oid: {}", line);
+                    continue;
+                }
+            };
+            if !oid.is_empty() && !name_part.is_empty() {
+                self.insert(&oid, name_part);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves `oid` to its longest known name prefix, joined with any
+    /// remaining numeric suffix (e.g. `snmpTrapOID.0`). Falls back to the
+    /// bare dotted numeric form when no prefix is known.
+    pub fn resolve(&self, oid: &[u32]) -> String {
+        let mut node = &self.root;
+        let mut best: Option<(usize, &str)> = None;
+
+        for (i, arc) in oid.iter().enumerate() {
+            let next = match node.children.get(arc) {
+                Some(next) => next,
+                None => break
+            };
+            node = next;
+            if let Some(ref name) = node.name {
+                best = Some((i + 1, name));
+            }
+        }
+
+        match best {
+            Some((consumed, name)) => {
+                let suffix: Vec<String> = oid[consumed..].iter().map(|a| a.to_string()).collect();
+                if suffix.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", name, suffix.join("."))
+                }
+            },
+            None => dotted(oid)
+        }
+    }
+}
+
+fn dotted(oid: &[u32]) -> String {
+    oid.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn resolve_finds_longest_known_prefix_and_appends_numeric_suffix() {
+        let map = OidMap::with_defaults();
+        assert_eq!(map.resolve(&[1,3,6,1,6,3,1,1,4,1,0]), "snmpTrapOID.0");
+        assert_eq!(map.resolve(&[1,3,6,1,2,1,1,3,0]), "sysUpTime.0");
+    }
+
+    #[test]
+    fn resolve_falls_back_to_dotted_numeric_form_when_unknown() {
+        let map = OidMap::new();
+        assert_eq!(map.resolve(&[1,2,3]), "1.2.3");
+    }
+
+    // A typo'd oid like `1.3.x.1` must not silently insert the wrong, shorter
+    // oid `[1,3,1]` by dropping the bad segment; the whole line is rejected.
+    #[test]
+    fn load_file_rejects_a_line_with_a_non_numeric_oid_segment() {
+        let mut path = std::env::temp_dir();
+        path.push("trapdoor-mib-test-invalid-oid.txt");
+        {
+            let mut f = File::create(&path).unwrap();
+            writeln!(f, "1.3.x.1 = bogus").unwrap();
+            writeln!(f, "1.3.6.1.4.1.9 = cisco").unwrap();
+        }
+
+        let mut map = OidMap::new();
+        map.load_file(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(map.resolve(&[1,3,1]), "1.3.1");
+        assert_eq!(map.resolve(&[1,3,6,1,4,1,9]), "cisco");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}